@@ -0,0 +1,39 @@
+//! Drive the serde cache round trip through the public API: build a BVH,
+//! flatten it to `CachedBVH` and back, then confirm `bounds()` and
+//! `traverse_packet` on the reloaded hierarchy agree with the original.
+
+extern crate tracer_bvh;
+
+use tracer_bvh::{BBox, Boundable, BVH};
+use tracer_bvh::vector::Vector;
+use tracer_bvh::ray::{Ray, RayPacket4};
+
+#[derive(Clone, Copy)]
+struct Block {
+    index: usize,
+}
+
+impl Boundable for Block {
+    type Payload = usize;
+    fn bounds(&self, _: f32, _: f32) -> BBox {
+        let c = self.index as f32;
+        BBox::span(Vector::new(c - 0.3, -0.3, -0.3), Vector::new(c + 0.3, 0.3, 0.3))
+    }
+    fn payload(&self) -> usize { self.index }
+}
+
+fn main() {
+    let blocks: Vec<Block> = (0..32).map(|i| Block { index: i }).collect();
+    let bvh = BVH::sah(4, blocks.clone());
+    let original_bounds = bvh.bounds();
+
+    let reloaded = BVH::from_cache(bvh.to_cache(), blocks).expect("primitive count matches");
+    println!("ORIGINAL_BOUNDS {:?} {:?}", original_bounds.min, original_bounds.max);
+    println!("RELOADED_BOUNDS {:?} {:?}", reloaded.bounds().min, reloaded.bounds().max);
+
+    let ray = Ray::new(Vector::new(-1.0, 0.0, 0.0), Vector::new(1.0, 0.0, 0.0));
+    let packet = RayPacket4::new([ray, ray, ray, ray]);
+    let mut hits: [Vec<usize>; 4] = Default::default();
+    reloaded.traverse_packet(&packet, &mut hits);
+    println!("RELOADED_PACKET_HITS {}", hits[0].len());
+}
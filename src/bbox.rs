@@ -5,10 +5,11 @@
 use std::f32;
 use std::ops::{Index, IndexMut};
 
-use linalg::{self, Vector, RayT, Axis};
+use linalg::{self, Vector, RayT, RayPacket4, Axis};
 
 /// A box between the min and max points
 #[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct BBox {
     pub min: Vector,
     pub max: Vector,
@@ -69,6 +70,32 @@ impl BBox {
         let d = self.max - self.min;
         2.0 * (d.x * d.y + d.x * d.z + d.y * d.z)
     }
+    /// Get the box shared by this box and the one passed. The result is
+    /// degenerate (some `max` component below its `min`) when they don't overlap
+    pub fn box_intersection(&self, b: &BBox) -> BBox {
+        BBox { min: Vector::new(f32::max(self.min.x, b.min.x), f32::max(self.min.y, b.min.y),
+                               f32::max(self.min.z, b.min.z)),
+               max: Vector::new(f32::min(self.max.x, b.max.x), f32::min(self.max.y, b.max.y),
+                               f32::min(self.max.z, b.max.z))
+        }
+    }
+    /// Compute the volume of the box
+    pub fn volume(&self) -> f32 {
+        let d = self.max - self.min;
+        d.x * d.y * d.z
+    }
+    /// Check if the box contains the point passed
+    pub fn contains(&self, p: &Vector) -> bool {
+        p.x >= self.min.x && p.x <= self.max.x
+            && p.y >= self.min.y && p.y <= self.max.y
+            && p.z >= self.min.z && p.z <= self.max.z
+    }
+    /// Check if this box overlaps the one passed
+    pub fn overlaps(&self, b: &BBox) -> bool {
+        self.min.x <= b.max.x && self.max.x >= b.min.x
+            && self.min.y <= b.max.y && self.max.y >= b.min.y
+            && self.min.z <= b.max.z && self.max.z >= b.min.z
+    }
     /// Optimized ray-box intersection test, for use in the BVH traversal where we have
     /// pre-computed the ray's inverse direction and which directions are negative, indicated
     /// by a 1 for negative and 0 for non-negative
@@ -104,6 +131,46 @@ impl BBox {
         }
         tmin < r.t_max() && tmax > r.t_min()
     }
+    /// Packet variant of `fast_intersect` testing four rays at once. The inverse
+    /// directions and negative-direction signs are precomputed per lane just as
+    /// in the scalar path. Returns a 4-bit mask with bit `i` set when lane `i`
+    /// hits the box.
+    pub fn fast_intersect_packet(&self, packet: &RayPacket4, inv_dirs: &[Vector; 4],
+                                 neg_dirs: &[[usize; 3]; 4]) -> u32 {
+        let mut mask = 0u32;
+        for lane in 0..4 {
+            if packet.active & (1 << lane) == 0 {
+                continue;
+            }
+            let o = packet.origins[lane];
+            let inv_dir = inv_dirs[lane];
+            let neg_dir = neg_dirs[lane];
+            // Check X & Y intersection
+            let mut tmin = (self[neg_dir[0]].x - o.x) * inv_dir.x;
+            let mut tmax = (self[1 - neg_dir[0]].x - o.x) * inv_dir.x;
+            let tymin = (self[neg_dir[1]].y - o.y) * inv_dir.y;
+            let tymax = (self[1 - neg_dir[1]].y - o.y) * inv_dir.y;
+            if tmin > tymax || tymin > tmax {
+                continue;
+            }
+            tmin = f32::max(tmin, tymin);
+            tmax = f32::min(tmax, tymax);
+
+            // Check Z intersection
+            let tzmin = (self[neg_dir[2]].z - o.z) * inv_dir.z;
+            let tzmax = (self[1 - neg_dir[2]].z - o.z) * inv_dir.z;
+            if tmin > tzmax || tzmin > tmax {
+                continue;
+            }
+            tmin = f32::max(tmin, tzmin);
+            tmax = f32::min(tmax, tzmax);
+
+            if tmin < packet.max_t[lane] && tmax > packet.min_t[lane] {
+                mask |= 1 << lane;
+            }
+        }
+        mask
+    }
 }
 
 impl Index<usize> for BBox {
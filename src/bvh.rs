@@ -0,0 +1,534 @@
+//! Provide a bounding volume hierarchy, `BVH`, built over a list of `Boundable`
+//! objects. The hierarchy can be constructed with an equal-count median split
+//! (`unanimated`) or with a Surface Area Heuristic binned split (`sah`).
+
+use std::f32;
+
+use partition::partition;
+use bbox::BBox;
+use linalg::{Axis, Vector, RayT, RayPacket4, Frustum};
+use Boundable;
+
+/// Number of bins used by the SAH binned split
+const SAH_BINS: usize = 12;
+/// Estimated cost of descending an interior node relative to a primitive test
+const TRAVERSAL_COST: f32 = 0.125;
+
+/// Cached bounds and centroid for a primitive, gathered once up front so the
+/// builder doesn't re-query `Boundable::bounds` for every candidate split
+#[derive(Copy, Clone)]
+struct PrimitiveInfo {
+    bounds: BBox,
+    centroid: Vector,
+}
+
+/// A node in the built hierarchy. Interior nodes own their two children, leaves
+/// reference a contiguous run of the reordered primitive list
+enum BuildNode {
+    Leaf { bounds: BBox, start: usize, count: usize },
+    Interior { bounds: BBox, children: Box<[BuildNode; 2]>, split_axis: Axis },
+}
+
+/// A compact node in the flattened hierarchy. Interior nodes lay their first
+/// child immediately after themselves in the array, so only the second child's
+/// index needs storing. A `count` of zero marks an interior node.
+#[derive(Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct LinearNode {
+    bounds: BBox,
+    /// Leaf: offset of the first primitive in `ordered`. Interior: index of the
+    /// second child in the node array.
+    offset: u32,
+    /// Number of primitives in a leaf, or 0 for an interior node
+    count: u16,
+    /// Split axis of an interior node (0 = X, 1 = Y, 2 = Z)
+    axis: u8,
+}
+
+/// A bounding volume hierarchy over a list of `Boundable` primitives
+pub struct BVH<T: Boundable> {
+    /// The maximum number of primitives stored in a leaf
+    max_leaf_size: usize,
+    /// The primitives the hierarchy was built over
+    objects: Vec<T>,
+    /// Maps the contiguous leaf slots back to indices into `objects`
+    ordered: Vec<usize>,
+    /// The root of the built tree, `None` if the hierarchy is empty
+    root: Option<BuildNode>,
+    /// The built tree flattened into a dense, pointer-free array for traversal
+    nodes: Vec<LinearNode>,
+    /// Height of the tree, i.e. the deepest root-to-leaf path. Traversal stacks
+    /// are sized from this so an unbalanced split chain can't overrun them.
+    max_depth: usize,
+}
+
+impl<T: Boundable> BVH<T> {
+    /// Build a BVH over the objects using an equal-count median split along the
+    /// longest axis of the centroid bounds. This assumes the geometry isn't
+    /// animated and queries each object's bounds at t = 0.
+    pub fn unanimated(max_leaf_size: usize, objects: Vec<T>) -> BVH<T> {
+        BVH::build(max_leaf_size, objects, SplitMethod::EqualCounts)
+    }
+    /// Build a BVH over the objects using a Surface Area Heuristic binned split.
+    /// SAH produces a hierarchy that is substantially cheaper to traverse for
+    /// non-uniform scenes at the cost of a more expensive build.
+    pub fn sah(max_leaf_size: usize, objects: Vec<T>) -> BVH<T> {
+        BVH::build(max_leaf_size, objects, SplitMethod::Sah)
+    }
+    /// Get the bounds of the whole hierarchy. Reads from the flattened node
+    /// array, like `traverse` and `cull`, so it works on a cache-loaded
+    /// hierarchy that has no tree form.
+    pub fn bounds(&self) -> BBox {
+        match self.nodes.first() {
+            Some(root) => root.bounds,
+            None => BBox::new(),
+        }
+    }
+    /// Traverse the flattened hierarchy with a packet of four rays, collecting
+    /// for each lane the primitives whose leaf bounds that lane intersects. The
+    /// whole packet descends together so interior node fetches are amortized
+    /// across the lanes, and a subtree is skipped as soon as no live lane hits
+    /// it. Uses an explicit stack instead of recursion, like `traverse` and
+    /// `cull`; each stack entry carries the mask that was still live when its
+    /// subtree was deferred, since a sibling can narrow the mask further before
+    /// that entry is resumed.
+    pub fn traverse_packet(&self, packet: &RayPacket4, hits: &mut [Vec<T::Payload>; 4]) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let neg_dirs = packet.neg_dirs();
+        let mut stack: Vec<(usize, u32)> = Vec::with_capacity(self.max_depth);
+        let mut current = 0;
+        let mut active = packet.active;
+        loop {
+            let node = &self.nodes[current];
+            let mask = node.bounds.fast_intersect_packet(packet, &packet.inv_dirs, &neg_dirs) & active;
+            if mask != 0 {
+                if node.count > 0 {
+                    let start = node.offset as usize;
+                    for slot in start..start + node.count as usize {
+                        let payload = self.objects[self.ordered[slot]].payload();
+                        for (lane, lane_hits) in hits.iter_mut().enumerate() {
+                            if mask & (1 << lane) != 0 {
+                                lane_hits.push(payload);
+                            }
+                        }
+                    }
+                    match stack.pop() {
+                        Some((n, m)) => { current = n; active = m; },
+                        None => break,
+                    }
+                } else {
+                    stack.push((node.offset as usize, mask));
+                    current += 1;
+                    active = mask;
+                }
+            } else {
+                match stack.pop() {
+                    Some((n, m)) => { current = n; active = m; },
+                    None => break,
+                }
+            }
+        }
+    }
+    /// Walk the hierarchy collecting the primitives whose bounds are inside or
+    /// intersect the frustum. A subtree is pruned as soon as its node's bounds
+    /// fall entirely outside one of the frustum planes.
+    pub fn cull(&self, frustum: &Frustum, results: &mut Vec<T::Payload>) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack: Vec<usize> = Vec::with_capacity(self.max_depth);
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current];
+            if frustum.intersects(&node.bounds) {
+                if node.count > 0 {
+                    let start = node.offset as usize;
+                    for slot in start..start + node.count as usize {
+                        results.push(self.objects[self.ordered[slot]].payload());
+                    }
+                    match stack.pop() {
+                        Some(n) => current = n,
+                        None => break,
+                    }
+                } else {
+                    stack.push(node.offset as usize);
+                    current += 1;
+                }
+            } else {
+                match stack.pop() {
+                    Some(n) => current = n,
+                    None => break,
+                }
+            }
+        }
+    }
+    /// Refit the hierarchy to the geometry at a new time interval without
+    /// changing its topology. Each leaf's bounds are recomputed from the objects
+    /// and the unions are propagated back up to the root in a single bottom-up
+    /// pass, which is far cheaper than a full rebuild for deforming meshes or
+    /// moving instances between frames.
+    ///
+    /// Returns a cheap quality heuristic: the SAH cost of the surviving
+    /// topology divided by the lower bound a fresh rebuild could reach. The
+    /// refit cost sums each interior node's surface area scaled by the
+    /// traversal cost and each leaf's surface area scaled by its primitive
+    /// count; the lower bound is the summed surface area of the individual
+    /// primitive bounds, which no leaf can undercut. The absolute value depends
+    /// on the tree's shape, so it isn't a fixed scale — call `refit` right after
+    /// a build to capture the tight baseline, then watch the value climb above
+    /// it as accumulated deformation inflates the node boxes relative to the
+    /// geometry they enclose; once it has grown well past the baseline a full
+    /// reconstruction is worthwhile.
+    ///
+    /// A cache-loaded hierarchy keeps only the flattened array and no tree form,
+    /// so it can't be refit in place: `refit` leaves it untouched and returns
+    /// `1.0`. Rebuild such a hierarchy before refitting. The `objects` must
+    /// describe the same primitives the tree was built over, in the same order;
+    /// a slice of a different length can't match the existing topology, so it is
+    /// left untouched and `1.0` returned rather than indexing out of bounds.
+    pub fn refit(&mut self, objects: Vec<T>, start: f32, end: f32) -> f32 {
+        if self.root.is_none() || objects.len() != self.ordered.len() {
+            return 1.0;
+        }
+        self.objects = objects;
+        let mut root = self.root.take();
+        // Accumulate the tight lower bound (summed primitive surface area) in
+        // the same pass that recomputes the leaf bounds, so the potentially
+        // expensive `bounds` query runs once per primitive.
+        let mut lower_bound = 0.0;
+        if let Some(ref mut r) = root {
+            self.refit_node(r, start, end, &mut lower_bound);
+        }
+        self.root = root;
+        // Re-derive the flat array from the refitted topology so every query
+        // path sees the new bounds
+        self.nodes.clear();
+        if let Some(ref r) = self.root {
+            Self::flatten(r, &mut self.nodes);
+        }
+        // Weigh the SAH cost of the refitted topology against the tightest a
+        // rebuild over the same primitives could reach. Unlike the root bounds
+        // (which refit reproduces exactly), the node costs inside the tree
+        // loosen as the geometry deforms, so the ratio actually moves.
+        // `self.root` is `Some` here: the early return above rejects an empty
+        // hierarchy, and `take`/restore leaves it populated.
+        let refit_cost = Self::sah_cost(self.root.as_ref().unwrap());
+        if lower_bound > 0.0 {
+            refit_cost / lower_bound
+        } else {
+            1.0
+        }
+    }
+    /// Sum the SAH cost of the subtree rooted at `node`: each interior node
+    /// contributes `TRAVERSAL_COST` times its surface area and each leaf its
+    /// surface area times the number of primitives it holds.
+    fn sah_cost(node: &BuildNode) -> f32 {
+        match *node {
+            BuildNode::Leaf { ref bounds, count, .. } =>
+                count as f32 * bounds.surface_area(),
+            BuildNode::Interior { ref bounds, ref children, .. } =>
+                TRAVERSAL_COST * bounds.surface_area()
+                    + Self::sah_cost(&children[0]) + Self::sah_cost(&children[1]),
+        }
+    }
+    /// Recompute the bounds of the subtree rooted at `node` from the objects,
+    /// returning the refitted bounds and accumulating each primitive's surface
+    /// area into `lower_bound` for the quality heuristic
+    fn refit_node(&self, node: &mut BuildNode, start: f32, end: f32, lower_bound: &mut f32) -> BBox {
+        match *node {
+            BuildNode::Leaf { ref mut bounds, start: first, count } => {
+                let mut b = BBox::new();
+                for slot in first..first + count {
+                    let pb = self.objects[self.ordered[slot]].bounds(start, end);
+                    *lower_bound += pb.surface_area();
+                    b = b.box_union(&pb);
+                }
+                *bounds = b;
+                b
+            },
+            BuildNode::Interior { ref mut bounds, ref mut children, .. } => {
+                let left = self.refit_node(&mut children[0], start, end, lower_bound);
+                let right = self.refit_node(&mut children[1], start, end, lower_bound);
+                *bounds = left.box_union(&right);
+                *bounds
+            },
+        }
+    }
+    /// Common build entry point shared by the different split methods
+    fn build(max_leaf_size: usize, objects: Vec<T>, method: SplitMethod) -> BVH<T> {
+        let info: Vec<_> = objects.iter().map(|o| {
+            let bounds = o.bounds(0.0, 0.0);
+            PrimitiveInfo { bounds: bounds, centroid: (bounds.min + bounds.max) * 0.5 }
+        }).collect();
+        let mut indices: Vec<usize> = (0..objects.len()).collect();
+        let mut ordered = Vec::with_capacity(objects.len());
+        let root = if indices.is_empty() {
+            None
+        } else {
+            Some(Self::build_recursive(&info, &mut indices[..], max_leaf_size, method, &mut ordered))
+        };
+        let mut nodes = Vec::new();
+        if let Some(ref r) = root {
+            Self::flatten(r, &mut nodes);
+        }
+        let max_depth = if nodes.is_empty() { 0 } else { Self::linear_height(&nodes, 0) };
+        BVH { max_leaf_size: max_leaf_size, objects: objects, ordered: ordered,
+              root: root, nodes: nodes, max_depth: max_depth }
+    }
+    /// Compute the height of the subtree rooted at node `i` of the flattened
+    /// array: the number of nodes on its deepest path down to a leaf
+    fn linear_height(nodes: &[LinearNode], i: usize) -> usize {
+        let node = &nodes[i];
+        if node.count > 0 {
+            1
+        } else {
+            1 + usize::max(Self::linear_height(nodes, i + 1),
+                           Self::linear_height(nodes, node.offset as usize))
+        }
+    }
+    /// Flatten the built tree into the dense node array, returning the index of
+    /// the node that was emitted for `node`. The first child is always the node
+    /// immediately following its parent, so only the second child is recorded.
+    fn flatten(node: &BuildNode, nodes: &mut Vec<LinearNode>) -> usize {
+        let my = nodes.len();
+        match *node {
+            BuildNode::Leaf { bounds, start, count } => {
+                nodes.push(LinearNode { bounds: bounds, offset: start as u32,
+                                        count: count as u16, axis: 0 });
+            },
+            BuildNode::Interior { bounds, ref children, split_axis } => {
+                nodes.push(LinearNode { bounds: bounds, offset: 0, count: 0,
+                                        axis: axis_index(split_axis) });
+                Self::flatten(&children[0], nodes);
+                let second = Self::flatten(&children[1], nodes);
+                nodes[my].offset = second as u32;
+            },
+        }
+        my
+    }
+    /// Traverse the flattened hierarchy with a single ray, collecting the
+    /// primitives whose leaf bounds the ray intersects. Uses an explicit stack
+    /// instead of recursion and visits the nearer child first, based on the
+    /// sign of the ray along each node's split axis.
+    pub fn traverse<R: RayT>(&self, ray: &R) -> Vec<T::Payload> {
+        let mut hits = Vec::new();
+        if self.nodes.is_empty() {
+            return hits;
+        }
+        let dir = ray.dir();
+        let inv_dir = Vector::new(1.0 / dir.x, 1.0 / dir.y, 1.0 / dir.z);
+        let neg_dir = [(dir.x < 0.0) as usize, (dir.y < 0.0) as usize, (dir.z < 0.0) as usize];
+        let mut stack: Vec<usize> = Vec::with_capacity(self.max_depth);
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current];
+            if node.bounds.fast_intersect(ray, &inv_dir, &neg_dir) {
+                if node.count > 0 {
+                    let start = node.offset as usize;
+                    for slot in start..start + node.count as usize {
+                        hits.push(self.objects[self.ordered[slot]].payload());
+                    }
+                    match stack.pop() {
+                        Some(n) => current = n,
+                        None => break,
+                    }
+                } else if neg_dir[node.axis as usize] == 1 {
+                    // Ray is negative along the split axis: visit the second
+                    // child first and defer the first (next) child
+                    stack.push(current + 1);
+                    current = node.offset as usize;
+                } else {
+                    stack.push(node.offset as usize);
+                    current += 1;
+                }
+            } else {
+                match stack.pop() {
+                    Some(n) => current = n,
+                    None => break,
+                }
+            }
+        }
+        hits
+    }
+    /// Recursively build a subtree over the primitives referenced by `indices`,
+    /// appending the primitives assigned to leaves to `ordered` in traversal order
+    fn build_recursive(info: &[PrimitiveInfo], indices: &mut [usize], max_leaf_size: usize,
+                       method: SplitMethod, ordered: &mut Vec<usize>) -> BuildNode {
+        let bounds = indices.iter().fold(BBox::new(), |b, &i| b.box_union(&info[i].bounds));
+        let count = indices.len();
+        // Build the bounds of the centroids to pick a split axis; if they're
+        // degenerate there is nothing to split on so emit a leaf
+        let centroid_bounds = indices.iter()
+            .fold(BBox::new(), |b, &i| b.point_union(info[i].centroid));
+        let axis = centroid_bounds.max_extent();
+        // Emit a leaf when there is nothing left to split on. The SAH path keeps
+        // splitting larger nodes since it decides for itself when a leaf is
+        // cheaper, but the equal-count path stops once the primitives fit.
+        let equal_count_leaf = match method {
+            SplitMethod::EqualCounts => count <= max_leaf_size,
+            SplitMethod::Sah => false,
+        };
+        if count <= 1 || equal_count_leaf || centroid_bounds.min[axis] == centroid_bounds.max[axis] {
+            return Self::make_leaf(indices, bounds, ordered);
+        }
+        let mid = match method {
+            SplitMethod::EqualCounts =>
+                Self::split_equal_counts(info, indices, &centroid_bounds, axis),
+            SplitMethod::Sah =>
+                match Self::split_sah(info, indices, &centroid_bounds, &bounds, axis, max_leaf_size) {
+                    Some(mid) => mid,
+                    None => return Self::make_leaf(indices, bounds, ordered),
+                },
+        };
+        let (left, right) = indices.split_at_mut(mid);
+        let children = Box::new([
+            Self::build_recursive(info, left, max_leaf_size, method, ordered),
+            Self::build_recursive(info, right, max_leaf_size, method, ordered),
+        ]);
+        BuildNode::Interior { bounds: bounds, children: children, split_axis: axis }
+    }
+    /// Emit a leaf covering `indices`, recording them in `ordered`
+    fn make_leaf(indices: &[usize], bounds: BBox, ordered: &mut Vec<usize>) -> BuildNode {
+        let start = ordered.len();
+        ordered.extend_from_slice(indices);
+        BuildNode::Leaf { bounds: bounds, start: start, count: indices.len() }
+    }
+    /// Partition the primitives into two equal-count halves about the midpoint
+    /// of the centroid bounds along `axis`, falling back to a median split if
+    /// the midpoint leaves one side empty
+    fn split_equal_counts(info: &[PrimitiveInfo], indices: &mut [usize],
+                          centroid_bounds: &BBox, axis: Axis) -> usize {
+        let midpoint = 0.5 * (centroid_bounds.min[axis] + centroid_bounds.max[axis]);
+        let mid = partition(indices.iter_mut(), |&i| info[i].centroid[axis] < midpoint);
+        if mid == 0 || mid == indices.len() {
+            indices.sort_by(|&a, &b| {
+                info[a].centroid[axis].partial_cmp(&info[b].centroid[axis]).unwrap()
+            });
+            indices.len() / 2
+        } else {
+            mid
+        }
+    }
+    /// Partition the primitives using the SAH binned split. Returns the split
+    /// index, or `None` if making a leaf is cheaper and permitted by the leaf size
+    fn split_sah(info: &[PrimitiveInfo], indices: &mut [usize], centroid_bounds: &BBox,
+                 bounds: &BBox, axis: Axis, max_leaf_size: usize) -> Option<usize> {
+        let count = indices.len();
+        // Accumulate the bounds and population of each bin
+        let mut bin_bounds = [BBox::new(); SAH_BINS];
+        let mut bin_counts = [0usize; SAH_BINS];
+        let bin_of = |i: usize| -> usize {
+            let b = (SAH_BINS as f32 * centroid_bounds.offset(&info[i].centroid)[axis]) as usize;
+            if b >= SAH_BINS { SAH_BINS - 1 } else { b }
+        };
+        for &i in indices.iter() {
+            let b = bin_of(i);
+            bin_bounds[b] = bin_bounds[b].box_union(&info[i].bounds);
+            bin_counts[b] += 1;
+        }
+        // Sweep the SAH_BINS - 1 candidate planes, accumulating the left box and
+        // count as a prefix sum and the right box and count as a suffix sum
+        let parent_sa = bounds.surface_area();
+        let mut cost = [0.0f32; SAH_BINS - 1];
+        let mut left_box = BBox::new();
+        let mut left_count = 0usize;
+        for (s, c) in cost.iter_mut().enumerate() {
+            left_box = left_box.box_union(&bin_bounds[s]);
+            left_count += bin_counts[s];
+            let mut right_box = BBox::new();
+            let mut right_count = 0usize;
+            for r in s + 1..SAH_BINS {
+                right_box = right_box.box_union(&bin_bounds[r]);
+                right_count += bin_counts[r];
+            }
+            *c = TRAVERSAL_COST
+                + (left_count as f32 * left_box.surface_area()
+                   + right_count as f32 * right_box.surface_area()) / parent_sa;
+        }
+        // Find the cheapest candidate plane
+        let mut best_split = 0;
+        let mut best_cost = cost[0];
+        for (s, &c) in cost.iter().enumerate().skip(1) {
+            if c < best_cost {
+                best_cost = c;
+                best_split = s;
+            }
+        }
+        // If a leaf is no more expensive than the best split and the primitives
+        // fit in a leaf, stop splitting
+        let leaf_cost = count as f32;
+        if best_cost >= leaf_cost && count <= max_leaf_size {
+            return None;
+        }
+        let mid = partition(indices.iter_mut(), |&i| bin_of(i) <= best_split);
+        // Guard against every primitive falling into one side
+        if mid == 0 || mid == count {
+            Some(count / 2)
+        } else {
+            Some(mid)
+        }
+    }
+}
+
+/// The flattened topology of a built `BVH`, detached from the primitive list so
+/// it can be written to disk alongside an asset and reloaded on a later run,
+/// skipping the expensive rebuild for unchanged geometry.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+pub struct CachedBVH {
+    max_leaf_size: usize,
+    nodes: Vec<LinearNode>,
+    ordered: Vec<usize>,
+    /// Number of primitives the topology was built over, validated on load
+    primitive_count: usize,
+}
+
+#[cfg(feature = "serde")]
+impl<T: Boundable> BVH<T> {
+    /// Capture the flattened topology for serialization
+    pub fn to_cache(&self) -> CachedBVH {
+        CachedBVH {
+            max_leaf_size: self.max_leaf_size,
+            nodes: self.nodes.clone(),
+            ordered: self.ordered.clone(),
+            primitive_count: self.objects.len(),
+        }
+    }
+    /// Rebuild a `BVH` from a cached topology and the objects it was built over.
+    /// Returns `None` if the primitive count doesn't match, since the cached
+    /// topology would then reference geometry that no longer exists. The tree
+    /// form is dropped on load, so cached hierarchies support array traversal
+    /// but must be rebuilt before an incremental `refit`.
+    pub fn from_cache(cache: CachedBVH, objects: Vec<T>) -> Option<BVH<T>> {
+        if cache.primitive_count != objects.len() {
+            return None;
+        }
+        let max_depth = if cache.nodes.is_empty() { 0 } else { Self::linear_height(&cache.nodes, 0) };
+        Some(BVH {
+            max_leaf_size: cache.max_leaf_size,
+            objects: objects,
+            ordered: cache.ordered,
+            root: None,
+            nodes: cache.nodes,
+            max_depth: max_depth,
+        })
+    }
+}
+
+/// Map a split axis to the byte stored in a `LinearNode`
+fn axis_index(a: Axis) -> u8 {
+    match a {
+        Axis::X => 0,
+        Axis::Y => 1,
+        Axis::Z => 2,
+    }
+}
+
+/// The split method to use while building the hierarchy
+#[derive(Copy, Clone)]
+enum SplitMethod {
+    EqualCounts,
+    Sah,
+}
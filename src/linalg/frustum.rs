@@ -0,0 +1,49 @@
+//! Provide a `Frustum` built from six plane half-spaces, used for culling the
+//! `BVH` against a view volume
+
+use bbox::BBox;
+use linalg::Vector;
+
+/// A plane half-space. A point `p` is considered inside when
+/// `normal.dot(p) + offset >= 0`.
+#[derive(Copy, Clone, Debug)]
+pub struct Plane {
+    pub normal: Vector,
+    pub offset: f32,
+}
+
+impl Plane {
+    /// Create a plane with the passed normal and offset
+    pub fn new(normal: Vector, offset: f32) -> Plane {
+        Plane { normal: normal, offset: offset }
+    }
+}
+
+/// A view frustum described by six bounding planes whose inward-facing normals
+/// point into the volume
+#[derive(Copy, Clone, Debug)]
+pub struct Frustum {
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Create a frustum from its six bounding planes
+    pub fn new(planes: [Plane; 6]) -> Frustum {
+        Frustum { planes: planes }
+    }
+    /// Test a box against the frustum. Returns true if the box is inside or
+    /// intersects the volume. For each plane the box's "positive vertex" is
+    /// chosen per axis from `min`/`max` following the sign of the plane normal;
+    /// if that vertex is on the negative side the box is fully outside.
+    pub fn intersects(&self, b: &BBox) -> bool {
+        for p in &self.planes {
+            let vertex = Vector::new(if p.normal.x >= 0.0 { b.max.x } else { b.min.x },
+                                     if p.normal.y >= 0.0 { b.max.y } else { b.min.y },
+                                     if p.normal.z >= 0.0 { b.max.z } else { b.min.z });
+            if p.normal.dot(&vertex) + p.offset < 0.0 {
+                return false;
+            }
+        }
+        true
+    }
+}
@@ -1,9 +1,13 @@
 pub mod vector;
 pub mod ray;
+pub mod frustum;
 
 pub use self::vector::Vector;
 pub use self::ray::Ray;
 pub use self::ray::RayT;
+pub use self::ray::RayPacket4;
+pub use self::frustum::Frustum;
+pub use self::frustum::Plane;
 
 use std::f32;
 use std::ops::{Mul, Add};
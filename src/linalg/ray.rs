@@ -0,0 +1,99 @@
+//! Provide the ray types used to query the acceleration structures
+
+use std::f32;
+
+use linalg::Vector;
+
+/// Trait implemented by the various ray types so `BBox` and the `BVH` can
+/// run their intersection tests against anything ray-like
+pub trait RayT {
+    /// Get the origin of the ray
+    fn origin(&self) -> Vector;
+    /// Get the direction of the ray
+    fn dir(&self) -> Vector;
+    /// Get the minimum t value along the ray that is considered a hit
+    fn t_min(&self) -> f32;
+    /// Get the maximum t value along the ray that is considered a hit
+    fn t_max(&self) -> f32;
+}
+
+/// A single ray with an origin, direction and the t range it is valid over
+#[derive(Copy, Clone, Debug)]
+pub struct Ray {
+    pub o: Vector,
+    pub d: Vector,
+    pub min_t: f32,
+    pub max_t: f32,
+}
+
+impl Ray {
+    /// Create a new ray from `o` in direction `d`, valid over the full positive range
+    pub fn new(o: Vector, d: Vector) -> Ray {
+        Ray { o: o, d: d, min_t: 0.0, max_t: f32::INFINITY }
+    }
+    /// Create a segment ray that is only valid over `[min_t, max_t]`
+    pub fn segment(o: Vector, d: Vector, min_t: f32, max_t: f32) -> Ray {
+        Ray { o: o, d: d, min_t: min_t, max_t: max_t }
+    }
+}
+
+impl RayT for Ray {
+    fn origin(&self) -> Vector {
+        self.o
+    }
+    fn dir(&self) -> Vector {
+        self.d
+    }
+    fn t_min(&self) -> f32 {
+        self.min_t
+    }
+    fn t_max(&self) -> f32 {
+        self.max_t
+    }
+}
+
+/// A packet of four rays traversed together. Coherent primary or shadow rays
+/// amortize node fetches by descending the hierarchy as a group. The inverse
+/// directions are precomputed once, mirroring the single-ray fast path, and the
+/// active mask tracks which of the four lanes are still live.
+#[derive(Copy, Clone, Debug)]
+pub struct RayPacket4 {
+    pub origins: [Vector; 4],
+    pub dirs: [Vector; 4],
+    pub inv_dirs: [Vector; 4],
+    pub min_t: [f32; 4],
+    pub max_t: [f32; 4],
+    /// Bit `i` is set while lane `i` is still active
+    pub active: u32,
+}
+
+impl RayPacket4 {
+    /// Gather four rays into a packet, precomputing the per-lane inverse
+    /// directions and marking every lane active
+    pub fn new(rays: [Ray; 4]) -> RayPacket4 {
+        let mut origins = [Vector::broadcast(0.0); 4];
+        let mut dirs = [Vector::broadcast(0.0); 4];
+        let mut inv_dirs = [Vector::broadcast(0.0); 4];
+        let mut min_t = [0.0f32; 4];
+        let mut max_t = [0.0f32; 4];
+        for (lane, r) in rays.iter().enumerate() {
+            origins[lane] = r.o;
+            dirs[lane] = r.d;
+            inv_dirs[lane] = Vector::new(1.0 / r.d.x, 1.0 / r.d.y, 1.0 / r.d.z);
+            min_t[lane] = r.min_t;
+            max_t[lane] = r.max_t;
+        }
+        RayPacket4 { origins: origins, dirs: dirs, inv_dirs: inv_dirs,
+                     min_t: min_t, max_t: max_t, active: 0b1111 }
+    }
+    /// Compute the per-lane sign of each direction component, matching the
+    /// `neg_dir` convention used by the single-ray traversal: 1 for negative,
+    /// 0 for non-negative
+    pub fn neg_dirs(&self) -> [[usize; 3]; 4] {
+        let mut neg = [[0usize; 3]; 4];
+        for (n, d) in neg.iter_mut().zip(self.dirs.iter()) {
+            *n = [(d.x < 0.0) as usize, (d.y < 0.0) as usize, (d.z < 0.0) as usize];
+        }
+        neg
+    }
+}
@@ -0,0 +1,97 @@
+//! Provide a 3 component vector type used throughout the library for points and directions
+
+use std::f32;
+use std::ops::{Add, Sub, Mul, Div, Neg, Index};
+
+use linalg::Axis;
+
+/// A 3 component vector
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Vector {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vector {
+    /// Create a vector with the passed components
+    pub fn new(x: f32, y: f32, z: f32) -> Vector {
+        Vector { x: x, y: y, z: z }
+    }
+    /// Create a vector with all components set to the same value
+    pub fn broadcast(x: f32) -> Vector {
+        Vector { x: x, y: x, z: x }
+    }
+    /// Compute the squared length of the vector
+    pub fn length_sqr(&self) -> f32 {
+        self.x * self.x + self.y * self.y + self.z * self.z
+    }
+    /// Compute the length of the vector
+    pub fn length(&self) -> f32 {
+        f32::sqrt(self.length_sqr())
+    }
+    /// Get a normalized copy of this vector
+    pub fn normalized(&self) -> Vector {
+        let len = self.length();
+        Vector::new(self.x / len, self.y / len, self.z / len)
+    }
+    /// Compute the dot product of this vector with another
+    pub fn dot(&self, b: &Vector) -> f32 {
+        self.x * b.x + self.y * b.y + self.z * b.z
+    }
+    /// Compute the cross product of this vector with another
+    pub fn cross(&self, b: &Vector) -> Vector {
+        Vector::new(self.y * b.z - self.z * b.y,
+                    self.z * b.x - self.x * b.z,
+                    self.x * b.y - self.y * b.x)
+    }
+}
+
+impl Add for Vector {
+    type Output = Vector;
+    fn add(self, rhs: Vector) -> Vector {
+        Vector::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
+}
+
+impl Sub for Vector {
+    type Output = Vector;
+    fn sub(self, rhs: Vector) -> Vector {
+        Vector::new(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+    }
+}
+
+impl Mul<f32> for Vector {
+    type Output = Vector;
+    fn mul(self, rhs: f32) -> Vector {
+        Vector::new(self.x * rhs, self.y * rhs, self.z * rhs)
+    }
+}
+
+impl Div for Vector {
+    type Output = Vector;
+    /// Component-wise division, used to map a point into a box's local space
+    fn div(self, rhs: Vector) -> Vector {
+        Vector::new(self.x / rhs.x, self.y / rhs.y, self.z / rhs.z)
+    }
+}
+
+impl Neg for Vector {
+    type Output = Vector;
+    fn neg(self) -> Vector {
+        Vector::new(-self.x, -self.y, -self.z)
+    }
+}
+
+impl Index<Axis> for Vector {
+    type Output = f32;
+    /// Access the vector's components by axis
+    fn index(&self, a: Axis) -> &f32 {
+        match a {
+            Axis::X => &self.x,
+            Axis::Y => &self.y,
+            Axis::Z => &self.z,
+        }
+    }
+}